@@ -6,98 +6,109 @@ pub struct AdsrParams {
     pub release_s: f64,
 }
 
-#[derive(Debug)]
-enum AdsrEnvelopeState {
-    Attack {
-        attack_start: f64,
-    },
-    Decay {
-        decay_start: f64,
-    },
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Attack,
+    Decay,
     Sustain,
-    Release {
-        start: f64,
-        /// Initial level from which the release begins
-        level: f64,
-    },
+    Release,
     Silent,
 }
-use AdsrEnvelopeState::*;
+use Stage::*;
+
+/// Floor applied to every segment's time constant, so that even a
+/// zero-length segment still takes a few milliseconds to approach its
+/// target instead of snapping there, which would click.
+const MIN_TAU_S: f64 = 0.003;
+
+/// How close `out` must get to a segment's target before the segment is
+/// considered finished and the envelope moves on to the next one.
+const EPSILON: f64 = 1e-3;
 
 pub struct AdsrEnvelope {
     params: AdsrParams,
-    state: AdsrEnvelopeState,
+    stage: Stage,
+    /// The envelope's current output level, updated each `sample` call by a
+    /// one-pole recurrence moving towards the active stage's target.
+    out: f64,
 }
 
 impl AdsrEnvelope {
     pub fn new(params: AdsrParams) -> AdsrEnvelope {
         AdsrEnvelope {
             params,
-            state: Silent,
+            stage: Silent,
+            out: 0.0,
         }
     }
 
-    pub fn trigger(&mut self, time: f64) {
-        self.state = AdsrEnvelopeState::Attack { attack_start: time };
+    /// True if this envelope has finished releasing and is producing silence.
+    pub fn is_silent(&self) -> bool {
+        self.stage == Silent
     }
 
-    pub fn release(&mut self, time: f64) {
-        match &self.state {
-            Attack { .. } | Decay { .. } | Sustain => {
-                self.state = Release {
-                    start: time,
-                    level: self.sample(time),
-                }
-            }
-            Silent | Release { .. } => (),
+    /// The level returned by the most recent call to `sample`, without
+    /// advancing the envelope.
+    pub fn level(&self) -> f64 {
+        self.out
+    }
+
+    /// Start (or restart) the attack segment.
+    ///
+    /// `out` is left wherever it currently is: the one-pole recurrence in
+    /// `sample` will smoothly approach the attack target from there, rather
+    /// than snapping back to zero, so a legato retrigger doesn't click.
+    pub fn trigger(&mut self) {
+        self.stage = Attack;
+    }
+
+    /// Retrigger this envelope in place with (possibly new) `params`.
+    ///
+    /// Unlike replacing the envelope with `AdsrEnvelope::new`, this leaves
+    /// `out` untouched so the attack ramps smoothly from whatever level the
+    /// envelope was already at, rather than clicking down to zero first.
+    pub fn retrigger(&mut self, params: AdsrParams) {
+        self.params = params;
+        self.trigger();
+    }
+
+    pub fn release(&mut self) {
+        match self.stage {
+            Attack | Decay | Sustain => self.stage = Release,
+            Silent | Release => (),
         }
     }
 
+    /// Advance the envelope by `dt` seconds and return the new level.
     // TODO: Move to a `Signal` trait or something.
-    pub fn sample(&mut self, time: f64) -> f64 {
+    pub fn sample(&mut self, dt: f64) -> f64 {
         loop {
-            match &self.state {
-                Silent => return 0.0,
-                Sustain => return self.params.sustain_level,
-                Attack { attack_start } => {
-                    let reltime = time - attack_start;
-                    if reltime < 0.0 {
-                        return 0.0;
-                    } else if reltime > self.params.attack_s {
-                        self.state = Decay {
-                            decay_start: attack_start + self.params.attack_s,
-                        };
-                    } else {
-                        return reltime / self.params.attack_s;
-                    }
-                }
-                Decay { decay_start } => {
-                    let reltime = time - decay_start;
-                    if reltime > self.params.decay_s || reltime < 0.0 {
-                        self.state = Sustain;
-                    } else {
-                        let alpha = 1.0
-                            - (reltime / self.params.decay_s) * (1.0 - self.params.sustain_level);
-                        assert!(alpha >= 0.0);
-                        assert!(alpha <= 1.0);
-                        return alpha;
-                    }
-                }
-                Release { start, level } => {
-                    let reltime = time - start;
-                    if reltime < 0.0 {
-                        return *level;
-                    }
-                    let alpha = level - (reltime / self.params.release_s);
-                    if alpha <= 0.0 {
-                        self.state = Silent;
-                        return 0.0;
-                    } else {
-                        assert!(alpha <= 1.0);
-                        return alpha;
-                    }
+            let (target, tau) = match self.stage {
+                Silent => return self.out,
+                Sustain => {
+                    self.out = self.params.sustain_level;
+                    return self.out;
                 }
+                Attack => (1.0, self.params.attack_s.max(MIN_TAU_S)),
+                Decay => (
+                    self.params.sustain_level,
+                    self.params.decay_s.max(MIN_TAU_S),
+                ),
+                Release => (0.0, self.params.release_s.max(MIN_TAU_S)),
+            };
+            self.out += (target - self.out) * (1.0 - (-dt / tau).exp());
+            if (self.out - target).abs() > EPSILON {
+                return self.out;
             }
+            // This segment has reached its target: snap to it exactly and
+            // fall through to the next segment within the same sample.
+            self.out = target;
+            self.stage = match self.stage {
+                Attack => Decay,
+                Decay => Sustain,
+                Release => Silent,
+                other => other,
+            };
         }
     }
 }
@@ -0,0 +1,19 @@
+/// A free-running low-frequency oscillator used to modulate pitch, filter
+/// cutoff, or amplitude.
+pub struct Lfo {
+    /// Normalized `[0, 1)` phase.
+    phase: f64,
+}
+
+impl Lfo {
+    pub fn new() -> Lfo {
+        Lfo { phase: 0.0 }
+    }
+
+    /// Advance the LFO by `dt` seconds at `rate_hz` and return its current
+    /// value, a triangle wave in `-1.0..=1.0`.
+    pub fn advance(&mut self, dt: f64, rate_hz: f64) -> f64 {
+        self.phase = (self.phase + dt * rate_hz).fract();
+        4.0 * (self.phase - (self.phase + 0.5).floor()).abs() - 1.0
+    }
+}
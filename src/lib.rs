@@ -5,6 +5,7 @@
 //! Based on the vst-rs `sine_synth` example and inspired by the Solar 50.
 
 mod adsr;
+mod lfo;
 mod midi;
 mod param;
 
@@ -20,17 +21,104 @@ use vst::event::Event;
 use vst::plugin::{CanDo, Category, HostCallback, Info, Plugin};
 
 use crate::adsr::{AdsrEnvelope, AdsrParams};
+use crate::lfo::Lfo;
 use crate::midi::MidiNote;
-use crate::param::Params;
+use crate::param::{LfoDest, Params};
 
 pub const TAU: f64 = PI * 2.0;
 
+/// Number of voices available for polyphony.
+///
+/// Notes beyond this count steal the quietest currently-sounding voice.
+const N_VOICES: usize = 8;
+
+/// Vibrato range: how many semitones the pitch swings at full LFO depth.
+const PITCH_LFO_SEMITONES: f64 = 2.0;
+
+/// Filter LFO range: how many octaves the cutoff swings at full LFO depth.
+const CUTOFF_LFO_OCTAVES: f64 = 2.0;
+
+/// Cross-FM range: how far osc2 can swing osc1's instantaneous frequency, in
+/// Hz, at full "FM Amount".
+const FM_AMOUNT_MAX_HZ: f64 = 2000.0;
+
+/// Feedback range: how far osc1's own last output can swing its instantaneous
+/// frequency, in Hz, at full "Feedback".
+const FEEDBACK_MAX_HZ: f64 = 500.0;
+
+/// A single playing note: its own envelope and oscillator phases.
+///
+/// A voice whose envelope `is_silent` is not actually sounding and is free to
+/// be reused by `note_on`; its `note` field is simply stale.
+struct Voice {
+    note: MidiNote,
+    envelope: AdsrEnvelope,
+    /// Normalized `[0, 1)` phase of each of the three oscillators.
+    osc_phase: [f64; 3],
+    /// Each oscillator's most recent output, used as a feedback source.
+    osc_last: [f64; 3],
+    /// State-variable filter state: low-pass and band-pass integrators.
+    filter_low: f64,
+    filter_band: f64,
+    /// The value of `Solar1::next_trigger_order` when this voice was last
+    /// triggered, so `note_off` can release the most recently triggered
+    /// instance of a repeated note rather than an arbitrary one.
+    trigger_order: u64,
+}
+
+impl Voice {
+    fn new() -> Voice {
+        Voice {
+            note: MidiNote(0),
+            envelope: AdsrEnvelope::new(AdsrParams {
+                attack_s: 0.0,
+                decay_s: 0.0,
+                sustain_level: 0.0,
+                release_s: 0.0,
+            }),
+            osc_phase: [0.0; 3],
+            osc_last: [0.0; 3],
+            filter_low: 0.0,
+            filter_band: 0.0,
+            trigger_order: 0,
+        }
+    }
+}
+
+/// The polynomial BLEP residual for a sawtooth: subtracting it from the
+/// naive ramp `2*t - 1` smooths the wrap discontinuity so the sawtooth
+/// doesn't alias as harshly at higher notes. It is zero away from the wrap.
+///
+/// `t` is the oscillator's normalized phase in `[0, 1)` and `dt` is how much
+/// the phase advances per sample.
+fn poly_blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let x = t / dt;
+        x + x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + x + x + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Advance a normalized oscillator phase by `dt`, wrapping back into `[0, 1)`.
+///
+/// `dt` may be negative or exceed 1.0 when FM or feedback pushes an
+/// oscillator's instantaneous frequency below zero or very high.
+fn advance_phase(t: f64, dt: f64) -> f64 {
+    (t + dt).rem_euclid(1.0)
+}
+
 struct Solar1 {
     sample_rate: f64,
-    time: f64,
-    note: Option<MidiNote>,
-    envelope: AdsrEnvelope,
+    voices: Vec<Voice>,
+    lfo: Lfo,
     parameters: Arc<Params>,
+    /// Incremented on every `note_on`, and stamped onto the triggered voice,
+    /// so `note_off` can tell which matching voice is the most recent one.
+    next_trigger_order: u64,
 }
 
 impl Solar1 {
@@ -57,19 +145,48 @@ impl Solar1 {
     }
 
     fn note_on(&mut self, note: MidiNote) {
-        // TODO: Keep a set of active notes and play with polyphony.
         let adsr_params = self.parameters.adsr();
         info!("note_on {note:?} {adsr_params:?}");
-        self.envelope = adsr::AdsrEnvelope::new(adsr_params);
-        self.envelope.trigger(self.time);
-        self.note = Some(note)
+        let voice_idx = self
+            .voices
+            .iter()
+            .position(|v| v.envelope.is_silent())
+            .unwrap_or_else(|| {
+                // All voices are busy: steal whichever is quietest right now.
+                self.voices
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| {
+                        a.envelope
+                            .level()
+                            .partial_cmp(&b.envelope.level())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .map(|(i, _)| i)
+                    .expect("voice pool is never empty")
+            });
+        self.next_trigger_order += 1;
+        let trigger_order = self.next_trigger_order;
+        let voice = &mut self.voices[voice_idx];
+        voice.note = note;
+        voice.envelope.retrigger(adsr_params);
+        voice.osc_phase = [0.0; 3];
+        voice.osc_last = [0.0; 3];
+        voice.filter_low = 0.0;
+        voice.filter_band = 0.0;
+        voice.trigger_order = trigger_order;
     }
 
     fn note_off(&mut self, note: MidiNote) {
         // info!("note_off {note:?}");
-        if self.note == Some(note) {
-            // This was the most recently played note?
-            self.envelope.release(self.time);
+        if let Some(voice) = self
+            .voices
+            .iter_mut()
+            .filter(|v| v.note == note && !v.envelope.is_silent())
+            .max_by_key(|v| v.trigger_order)
+        {
+            // This was the most recently triggered instance of this note.
+            voice.envelope.release();
         }
         // Don't forget the note; let it ring out.
     }
@@ -80,15 +197,15 @@ impl Plugin for Solar1 {
         let _ = SimpleLogger::new().init(); // It might be already initialized; we don't care.
 
         let parameters = Params::default();
-        let envelope = adsr::AdsrEnvelope::new(parameters.adsr());
+        let voices = (0..N_VOICES).map(|_| Voice::new()).collect();
 
         info!("Solar1 created!");
         Solar1 {
             sample_rate: 44100.0,
-            time: 0.0,
-            note: None,
-            envelope,
+            voices,
+            lfo: Lfo::new(),
             parameters: Arc::new(parameters),
+            next_trigger_order: 0,
         }
     }
 
@@ -126,30 +243,90 @@ impl Plugin for Solar1 {
         let (_, mut outputs) = buffer.split();
         let output_count = outputs.len();
         let per_sample = self.time_per_sample();
-        let mut output_sample;
         for sample_idx in 0..samples {
-            let time = self.time;
-            if let Some(current_note) = &self.note {
-                let base_freq = current_note.frequency();
-                // What position are we at in this cycle?
-                let signal0 = (time * base_freq) % 1.0 - 0.5;
+            let lfo_value = self.lfo.advance(per_sample, self.parameters.lfo_rate_hz());
+            let lfo_depth = self.parameters.lfo_depth();
+            let cutoff_hz = self.parameters.cutoff_hz();
+            let (pitch_mul, cutoff_hz, amp_mul) = match self.parameters.lfo_dest() {
+                LfoDest::Pitch => (
+                    2f64.powf(lfo_value * lfo_depth * PITCH_LFO_SEMITONES / 12.0),
+                    cutoff_hz,
+                    1.0,
+                ),
+                LfoDest::Cutoff => (
+                    1.0,
+                    cutoff_hz * 2f64.powf(lfo_value * lfo_depth * CUTOFF_LFO_OCTAVES),
+                    1.0,
+                ),
+                LfoDest::Amplitude => (1.0, cutoff_hz, (1.0 + lfo_value * lfo_depth).max(0.0)),
+            };
+
+            let active_voices = self
+                .voices
+                .iter()
+                .filter(|v| !v.envelope.is_silent())
+                .count();
+            // Scale down as more voices sound together, so a full chord
+            // doesn't clip harder than a single note.
+            let voice_gain = 1.0 / (active_voices.max(1) as f64).sqrt();
+
+            let mut signal_sum = 0.0;
+            for voice in self.voices.iter_mut() {
+                if voice.envelope.is_silent() {
+                    continue;
+                }
+                let base_freq = voice.note.frequency() * pitch_mul;
+
+                let dt0 = base_freq / self.sample_rate;
+                let t0 = voice.osc_phase[0];
+                let signal0 = (2.0 * t0 - 1.0) - poly_blep(t0, dt0);
+                voice.osc_phase[0] = advance_phase(t0, dt0);
+                voice.osc_last[0] = signal0;
 
-                let signal1 = (time * base_freq * self.parameters.osc1_freq_mul()) % 1.0 - 0.5;
+                // osc2 is computed first so it can frequency-modulate osc1.
+                let dt2 = base_freq * self.parameters.osc2_freq_mul() / self.sample_rate;
+                let t2 = voice.osc_phase[2];
+                let signal2 = (2.0 * t2 - 1.0) - poly_blep(t2, dt2);
+                voice.osc_phase[2] = advance_phase(t2, dt2);
+                voice.osc_last[2] = signal2;
 
-                let signal2 = (time * base_freq * self.parameters.osc2_freq_mul()) % 1.0 - 0.5;
+                let fm_hz = self.parameters.fm_amount() * FM_AMOUNT_MAX_HZ * signal2;
+                let feedback_hz = self.parameters.feedback() * FEEDBACK_MAX_HZ * voice.osc_last[1];
+                let dt1 = (base_freq * self.parameters.osc1_freq_mul() + fm_hz + feedback_hz)
+                    / self.sample_rate;
+                let t1 = voice.osc_phase[1];
+                let signal1 = (2.0 * t1 - 1.0) - poly_blep(t1, dt1.abs().max(f64::EPSILON));
+                voice.osc_phase[1] = advance_phase(t1, dt1);
+                voice.osc_last[1] = signal1;
 
                 let signal = signal0
                     + signal1 * self.parameters.osc1_level()
                     + signal2 * self.parameters.osc2_level();
 
-                let alpha = self.envelope.sample(time);
+                // Chamberlin state-variable low-pass filter, run per voice so
+                // its state stays consistent as voices are stolen and reused.
+                //
+                // The recurrence below is only stable for `q > 0` and
+                // `0 < f < sqrt(q * q + 4) - q`; both bounds are reachable
+                // from normal knob positions (cutoff near or, with the LFO,
+                // above Nyquist; resonance at its maximum), so clamp away
+                // from them rather than letting the state diverge to
+                // NaN/Infinity and stay broken until the next `note_on`.
+                let q = (1.0 - self.parameters.resonance()).max(0.01);
+                let f_max = 0.99 * ((q * q + 4.0).sqrt() - q);
+                let nyquist_hz = 0.5 * self.sample_rate;
+                let f = (2.0 * (PI * cutoff_hz.min(nyquist_hz) / self.sample_rate).sin()).min(f_max);
+                voice.filter_low += f * voice.filter_band;
+                let high = signal - voice.filter_low - q * voice.filter_band;
+                voice.filter_band += f * high;
 
-                output_sample = (signal * alpha) as f32;
+                let alpha = voice.envelope.sample(per_sample);
 
-                self.time += per_sample;
-            } else {
-                output_sample = 0.0;
+                signal_sum += voice.filter_low * alpha * amp_mul;
             }
+            // Soft-clip rather than hard-clip any remaining overshoot, e.g.
+            // from a resonant filter peak.
+            let output_sample = (signal_sum * voice_gain).tanh() as f32;
             // Output this value in unison across probably two stereo output channels.
             for buf_idx in 0..output_count {
                 let buff = outputs.get_mut(buf_idx);
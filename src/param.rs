@@ -1,10 +1,26 @@
 use std::cell::Cell;
 use std::sync::Mutex;
 
+use log::warn;
 use vst::plugin::PluginParameters;
 
 use crate::AdsrParams;
 
+/// Magic number identifying a Solar1 preset chunk, to avoid misreading a
+/// chunk written by some unrelated plugin.
+const CHUNK_MAGIC: u32 = 0x534f_4c31; // "SOL1"
+
+/// Size in bytes of the chunk header: the magic number, followed by the
+/// number of parameters serialized after it.
+///
+/// The chunk is self-describing by parameter count rather than carrying a
+/// separate format version, since `N_PARAM` has already grown several times
+/// as parameters were added; a version number would have needed bumping
+/// every time and nothing enforced that. Storing the actual count lets an
+/// older/shorter chunk be loaded (missing trailing parameters keep their
+/// defaults) instead of being rejected outright as if it were corrupt.
+const CHUNK_HEADER_LEN: usize = 8;
+
 // Parameter assignments
 const ATTACK: usize = 0;
 const DECAY: usize = 1;
@@ -14,12 +30,38 @@ const OSC1_TUNE: usize = 4;
 const OSC1_LEVEL: usize = 5;
 const OSC2_TUNE: usize = 6;
 const OSC2_LEVEL: usize = 7;
+const CUTOFF: usize = 8;
+const RESONANCE: usize = 9;
+const LFO_RATE: usize = 10;
+const LFO_DEPTH: usize = 11;
+const LFO_DEST: usize = 12;
+const FM_AMOUNT: usize = 13;
+const FEEDBACK: usize = 14;
 
-const N_PARAM: usize = 8;
+const N_PARAM: usize = 15;
 
 // Scaling factors from the [0..1] range to the semantic range.
 const RELEASE_SCALE: f32 = 10.0;
 
+// `Cutoff`'s Exp2 curve bounds, chosen so 0..1 spans roughly 40 Hz to 18 kHz.
+const CUTOFF_EXP2_MIN: f32 = 5.321_928; // log2(40)
+const CUTOFF_EXP2_MAX: f32 = 14.135_709; // log2(18_000)
+
+// `LFO Rate`'s Exp2 curve bounds, chosen so 0..1 spans roughly 0.1 to 20 Hz.
+const LFO_RATE_EXP2_MIN: f32 = -3.321_928; // log2(0.1)
+const LFO_RATE_EXP2_MAX: f32 = 4.321_928; // log2(20)
+
+/// Where the LFO's output is routed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LfoDest {
+    /// Vibrato: modulates the oscillators' frequency.
+    Pitch,
+    /// Modulates the state-variable filter's cutoff.
+    Cutoff,
+    /// Tremolo: modulates the overall output amplitude.
+    Amplitude,
+}
+
 /// A scaling curve between the [0..1] range of a parameter, and the value
 /// used by the synth and shown in its text.
 enum Curve {
@@ -111,6 +153,48 @@ const PARAMS: [ParamDef; N_PARAM] = [
         default: 0.5,
         curve: Curve::Identity,
     },
+    ParamDef {
+        name: "Cutoff",
+        label: "Hz",
+        default: 1.0,
+        curve: Curve::Exp2(CUTOFF_EXP2_MIN, CUTOFF_EXP2_MAX),
+    },
+    ParamDef {
+        name: "Resonance",
+        label: "",
+        default: 0.2,
+        curve: Curve::Identity,
+    },
+    ParamDef {
+        name: "LFO Rate",
+        label: "Hz",
+        default: 0.4,
+        curve: Curve::Exp2(LFO_RATE_EXP2_MIN, LFO_RATE_EXP2_MAX),
+    },
+    ParamDef {
+        name: "LFO Depth",
+        label: "",
+        default: 0.0,
+        curve: Curve::Identity,
+    },
+    ParamDef {
+        name: "LFO Dest",
+        label: "",
+        default: 0.0,
+        curve: Curve::Identity,
+    },
+    ParamDef {
+        name: "FM Amount",
+        label: "",
+        default: 0.0,
+        curve: Curve::Identity,
+    },
+    ParamDef {
+        name: "Feedback",
+        label: "",
+        default: 0.0,
+        curve: Curve::Identity,
+    },
 ];
 
 /// Plugin parameters: these map into knobs or sliders in the DAW.
@@ -147,6 +231,45 @@ impl Params {
         self.copy_params()[OSC2_LEVEL] as f64
     }
 
+    /// Return the state-variable filter's cutoff frequency, in Hz.
+    pub fn cutoff_hz(&self) -> f64 {
+        self.scaled_value(CUTOFF) as f64
+    }
+
+    /// Return the state-variable filter's resonance, in `0..1`.
+    pub fn resonance(&self) -> f64 {
+        self.copy_params()[RESONANCE] as f64
+    }
+
+    /// Return the LFO's rate, in Hz.
+    pub fn lfo_rate_hz(&self) -> f64 {
+        self.scaled_value(LFO_RATE) as f64
+    }
+
+    /// Return the LFO's modulation depth, in `0..1`.
+    pub fn lfo_depth(&self) -> f64 {
+        self.copy_params()[LFO_DEPTH] as f64
+    }
+
+    /// Return where the LFO is routed.
+    pub fn lfo_dest(&self) -> LfoDest {
+        match self.copy_params()[LFO_DEST] {
+            v if v < 1.0 / 3.0 => LfoDest::Pitch,
+            v if v < 2.0 / 3.0 => LfoDest::Cutoff,
+            _ => LfoDest::Amplitude,
+        }
+    }
+
+    /// Return how strongly osc2 frequency-modulates osc1, in `0..1`.
+    pub fn fm_amount(&self) -> f64 {
+        self.copy_params()[FM_AMOUNT] as f64
+    }
+
+    /// Return how strongly osc1 feeds back into its own phase, in `0..1`.
+    pub fn feedback(&self) -> f64 {
+        self.copy_params()[FEEDBACK] as f64
+    }
+
     /// Return global ADSR parameters.
     pub fn adsr(&self) -> AdsrParams {
         let p = self.copy_params();
@@ -211,4 +334,80 @@ impl PluginParameters for Params {
     fn get_parameter_label(&self, index: i32) -> String {
         PARAMS[index as usize].label.to_owned()
     }
+
+    /// Serialize the current patch as a preset chunk: a small header
+    /// followed by each parameter's scaled (semantic) value, so the chunk
+    /// stays meaningful even if a parameter's `Curve` changes. Values are
+    /// stored in `PARAMS` order with no names, and are loaded back by index.
+    fn get_preset_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(CHUNK_HEADER_LEN + N_PARAM * 4);
+        data.extend_from_slice(&CHUNK_MAGIC.to_le_bytes());
+        data.extend_from_slice(&(N_PARAM as u32).to_le_bytes());
+        for i in 0..N_PARAM {
+            data.extend_from_slice(&self.scaled_value(i).to_le_bytes());
+        }
+        data
+    }
+
+    /// Solar1 only has a single program, so the bank chunk is just the one
+    /// preset.
+    fn get_bank_data(&self) -> Vec<u8> {
+        self.get_preset_data()
+    }
+
+    fn load_preset_data(&self, data: &[u8]) {
+        if data.len() < CHUNK_HEADER_LEN {
+            warn!(
+                "ignoring preset chunk shorter than its header ({} bytes)",
+                data.len()
+            );
+            return;
+        }
+        let magic = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        if magic != CHUNK_MAGIC {
+            warn!("ignoring preset chunk with unrecognized magic ({magic:#x})");
+            return;
+        }
+        let stored_param_count = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        if data.len() != CHUNK_HEADER_LEN + stored_param_count * 4 {
+            warn!(
+                "ignoring preset chunk with inconsistent length ({} bytes for {} params)",
+                data.len(),
+                stored_param_count
+            );
+            return;
+        }
+        if stored_param_count != N_PARAM {
+            // Likely a preset saved by an older or newer build: load
+            // whatever parameters overlap and leave the rest at default.
+            warn!(
+                "preset chunk has {stored_param_count} params, this build has {N_PARAM}; loading the overlap"
+            );
+        }
+        let mut p = Params::default().copy_params();
+        for (i, def) in PARAMS.iter().enumerate().take(stored_param_count) {
+            let start = CHUNK_HEADER_LEN + i * 4;
+            let scaled = f32::from_le_bytes(data[start..start + 4].try_into().unwrap());
+            // Clamp back through the curve so a corrupt or hand-edited chunk
+            // can't push a parameter outside its valid range. `reverse` can
+            // return NaN for a value outside the curve's domain (e.g. a
+            // non-positive `Exp2` input), which `clamp` would pass straight
+            // through, so fall back to the default in that case.
+            let normalized = def.curve.reverse(scaled);
+            p[i] = if normalized.is_finite() {
+                normalized.clamp(0.0, 1.0)
+            } else {
+                warn!(
+                    "preset chunk has out-of-domain value {scaled} for parameter {:?}; using default",
+                    def.name
+                );
+                def.default
+            };
+        }
+        self.p.lock().unwrap().set(p);
+    }
+
+    fn load_bank_data(&self, data: &[u8]) {
+        self.load_preset_data(data)
+    }
 }